@@ -0,0 +1,78 @@
+use std::fmt;
+
+/// Errors that can occur while building or parsing a flattened device tree blob.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    /// A node name contained a `/`, which is reserved as the path separator.
+    InvalidNodeName,
+    /// A string property value contained an interior NUL byte.
+    InvalidString,
+    /// A property was added before any node was opened with `begin_node`.
+    PropertyBeforeBeginNode,
+    /// A property was added after the root node was closed.
+    PropertyAfterEndNode,
+    /// `finish` (or `finish_into`) was called while a node was still open.
+    UnclosedNode,
+    /// `end_node` was called for a node other than the innermost open one.
+    OutOfOrderEndNode,
+    /// `property_phandle` was called with a phandle value already in use.
+    DuplicatePhandle,
+    /// `label_node` was called with a label that is already attached to a node.
+    DuplicateLabel,
+    /// `property_phandle_ref` referenced a phandle that was never allocated.
+    UnknownPhandle,
+    /// The serialized tree would not fit in the caller-provided buffer, or
+    /// would overflow the 32-bit `totalsize` header field.
+    TotalSizeTooLarge,
+    /// `alloc_phandle` could not allocate another phandle because
+    /// `u32::MAX` is already in use.
+    PhandleSpaceExhausted,
+    /// The blob is shorter than the 40-byte FDT header or its declared
+    /// `totalsize`, or a name or property value runs past the end of its block.
+    Truncated,
+    /// The blob's magic number, version, or a block offset or size derived
+    /// from the header, was not valid.
+    BadHeader,
+    /// An unrecognized or out-of-place token was encountered in the
+    /// structure block, or a name, property value or overlay fixup entry
+    /// could not be decoded or pointed outside its property.
+    BadToken,
+    /// A `phandle` or other lookup (node path, label, ...) did not resolve.
+    NotFound,
+    /// An overlay's `__local_fixups__` node referenced a label that was
+    /// never given a phandle, which this simplified overlay model cannot
+    /// resolve.
+    UnresolvedFixup,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidNodeName => write!(f, "node name contains a '/'"),
+            Error::InvalidString => write!(f, "string property contains an interior NUL byte"),
+            Error::PropertyBeforeBeginNode => {
+                write!(f, "property added before any node was opened")
+            }
+            Error::PropertyAfterEndNode => write!(f, "property added after the root node closed"),
+            Error::UnclosedNode => write!(f, "finish called with an unclosed node"),
+            Error::OutOfOrderEndNode => write!(f, "end_node called out of LIFO order"),
+            Error::DuplicatePhandle => write!(f, "phandle value is already in use"),
+            Error::DuplicateLabel => write!(f, "label is already attached to another node"),
+            Error::UnknownPhandle => write!(f, "phandle was never allocated"),
+            Error::TotalSizeTooLarge => write!(f, "serialized tree does not fit"),
+            Error::PhandleSpaceExhausted => write!(f, "no phandle values remain to allocate"),
+            Error::Truncated => write!(f, "blob is truncated"),
+            Error::BadHeader => write!(f, "blob header is invalid"),
+            Error::BadToken => write!(f, "structure block contains an invalid token"),
+            Error::NotFound => write!(f, "requested node or property was not found"),
+            Error::UnresolvedFixup => {
+                write!(f, "overlay has a local phandle reference that was never assigned")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Convenience alias for results returned by this crate.
+pub type Result<T> = std::result::Result<T, Error>;
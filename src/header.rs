@@ -0,0 +1,84 @@
+//! Shared low-level helpers for reading the FDT header and the token stream,
+//! used by both [`crate::reader`] (full parse) and [`crate::writer`]
+//! (reopening a blob for `from_blob`).
+
+use crate::{Error, Result, FDT_HEADER_SIZE, FDT_MAGIC, FDT_VERSION};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Header {
+    pub(crate) off_dt_struct: usize,
+    pub(crate) off_dt_strings: usize,
+    pub(crate) off_mem_rsvmap: usize,
+    pub(crate) size_dt_strings: usize,
+    pub(crate) size_dt_struct: usize,
+    pub(crate) boot_cpuid_phys: u32,
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes: [u8; 4] = data
+        .get(offset..offset + 4)
+        .ok_or(Error::Truncated)?
+        .try_into()
+        .map_err(|_| Error::Truncated)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+/// Reads a NUL-terminated string starting at `start`, returning it along
+/// with the offset of the byte just past the NUL.
+pub(crate) fn read_cstr(data: &[u8], start: usize) -> Result<(&str, usize)> {
+    let nul_offset = data
+        .get(start..)
+        .ok_or(Error::Truncated)?
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(Error::Truncated)?;
+    let end = start + nul_offset;
+    let s = std::str::from_utf8(&data[start..end]).map_err(|_| Error::BadToken)?;
+    Ok((s, end + 1))
+}
+
+/// Validates the 40-byte header and the offsets/lengths derived from it,
+/// returning them for the caller to slice the blob with.
+pub(crate) fn parse_header(data: &[u8]) -> Result<Header> {
+    if data.len() < FDT_HEADER_SIZE {
+        return Err(Error::Truncated);
+    }
+    if read_u32(data, 0)? != FDT_MAGIC {
+        return Err(Error::BadHeader);
+    }
+
+    let totalsize = read_u32(data, 4)? as usize;
+    let off_dt_struct = read_u32(data, 8)? as usize;
+    let off_dt_strings = read_u32(data, 12)? as usize;
+    let off_mem_rsvmap = read_u32(data, 16)? as usize;
+    let version = read_u32(data, 20)?;
+    let last_comp_version = read_u32(data, 24)?;
+    if version < FDT_VERSION || last_comp_version > FDT_VERSION {
+        return Err(Error::BadHeader);
+    }
+    let boot_cpuid_phys = read_u32(data, 28)?;
+    let size_dt_strings = read_u32(data, 32)? as usize;
+    let size_dt_struct = read_u32(data, 36)? as usize;
+
+    if totalsize > data.len() {
+        return Err(Error::Truncated);
+    }
+    let struct_end = off_dt_struct
+        .checked_add(size_dt_struct)
+        .ok_or(Error::BadHeader)?;
+    let strings_end = off_dt_strings
+        .checked_add(size_dt_strings)
+        .ok_or(Error::BadHeader)?;
+    if struct_end > totalsize || strings_end > totalsize {
+        return Err(Error::BadHeader);
+    }
+
+    Ok(Header {
+        off_dt_struct,
+        off_dt_strings,
+        off_mem_rsvmap,
+        size_dt_strings,
+        size_dt_struct,
+        boot_cpuid_phys,
+    })
+}
@@ -0,0 +1,40 @@
+//! A pure-Rust encoder and decoder for [flattened device tree](https://www.devicetree.org/)
+//! (FDT / DTB) blobs, for use by VMMs that need to hand a device tree to a guest kernel.
+//!
+//! This is an arceos-flavored derivative of the `vm-fdt` crate used by crosvm and
+//! cloud-hypervisor: [`FdtWriter`] builds a blob from scratch (or appends to one produced
+//! elsewhere), and [`FdtReader`] walks a blob back into nodes and properties.
+
+mod error;
+mod header;
+mod overlay;
+mod reader;
+mod writer;
+
+pub use error::{Error, Result};
+pub use overlay::apply_overlay;
+pub use reader::FdtReader;
+pub use writer::{FdtReserveEntry, FdtWriter, FdtWriterNode, NodeBuilder};
+
+/// Magic number at the start of every FDT blob (big-endian on the wire).
+pub(crate) const FDT_MAGIC: u32 = 0xd00dfeed;
+
+/// Device tree version emitted by this crate and expected on read.
+pub(crate) const FDT_VERSION: u32 = 17;
+pub(crate) const FDT_LAST_COMP_VERSION: u32 = 16;
+
+/// Structure block tokens, as defined by the device tree specification.
+pub(crate) const FDT_BEGIN_NODE: u32 = 0x0000_0001;
+pub(crate) const FDT_END_NODE: u32 = 0x0000_0002;
+pub(crate) const FDT_PROP: u32 = 0x0000_0003;
+pub(crate) const FDT_NOP: u32 = 0x0000_0004;
+pub(crate) const FDT_END: u32 = 0x0000_0009;
+
+/// Size in bytes of the fixed FDT header.
+pub(crate) const FDT_HEADER_SIZE: usize = 40;
+
+/// Pads `len` up to the next multiple of 4, as required between structure
+/// block tokens and values.
+pub(crate) const fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
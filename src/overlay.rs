@@ -0,0 +1,185 @@
+//! Applies a device tree overlay blob to a base blob.
+//!
+//! This is a simplified subset of the real device tree overlay spec: there is
+//! no `/fragment@N/__overlay__` wrapper or `target`/`target-path` indirection
+//! — an overlay's top-level children become direct children of the base
+//! tree's root. Cross-tree phandle references recorded in the overlay's
+//! `__fixups__` node (see [`FdtWriter::property_phandle_ref_label`]) are
+//! resolved against the base tree's `__symbols__` node before the overlay's
+//! nodes are copied in. A reference left in `__local_fixups__` (a same-tree
+//! label that was never given a phandle) cannot be resolved by this
+//! simplified model and makes [`apply_overlay`] fail with
+//! [`Error::UnresolvedFixup`] rather than silently copying the referencing
+//! node with a dangling zero phandle cell.
+
+use crate::header::{parse_header, read_cstr, read_u32};
+use crate::{align4, Error, FdtReader, FdtWriter, Result, FDT_BEGIN_NODE, FDT_END_NODE, FDT_PROP};
+
+/// Applies `overlay_blob` to `base_blob`, resolving the overlay's external
+/// phandle references against the base tree's `__symbols__` node and
+/// returning the merged blob.
+///
+/// Fails with [`Error::UnresolvedFixup`] if the overlay still carries a
+/// `__local_fixups__` entry, since this simplified model has no way to
+/// assign the missing phandle that entry is waiting on.
+pub fn apply_overlay(base_blob: &[u8], overlay_blob: &[u8]) -> Result<Vec<u8>> {
+    let base_reader = FdtReader::new(base_blob)?;
+
+    let mut patched_overlay = overlay_blob.to_vec();
+    if let Ok(fixups) = base_reader_fixups(&FdtReader::new(&patched_overlay)?) {
+        for (label, triplets) in fixups {
+            let path = resolve_symbol(&base_reader, &label)?;
+            let phandle_bytes = base_reader.get_property(&path, "phandle")?;
+            let phandle: [u8; 4] = phandle_bytes.try_into().map_err(|_| Error::BadToken)?;
+
+            for triplet in triplets {
+                let (node_path, property, offset) = parse_triplet(&triplet)?;
+                let (value_offset, len) =
+                    find_property_value_offset(&patched_overlay, &node_path, property)?;
+                if offset.checked_add(4).filter(|&end| end <= len).is_none() {
+                    return Err(Error::BadToken);
+                }
+                patched_overlay[value_offset + offset..value_offset + offset + 4]
+                    .copy_from_slice(&phandle);
+            }
+        }
+    }
+
+    let overlay_reader = FdtReader::new(&patched_overlay)?;
+    if overlay_reader.nodes().any(|path| path == "/__local_fixups__") {
+        return Err(Error::UnresolvedFixup);
+    }
+
+    let (mut writer, root) = FdtWriter::from_blob(base_blob)?;
+    for child in direct_children(&overlay_reader, "/") {
+        copy_subtree(&mut writer, &overlay_reader, child)?;
+    }
+    writer.end_node(root)?;
+    writer.finish()
+}
+
+fn resolve_symbol(reader: &FdtReader, label: &str) -> Result<String> {
+    let path_bytes = reader.get_property("/__symbols__", label)?;
+    let path = std::str::from_utf8(path_bytes)
+        .map_err(|_| Error::BadToken)?
+        .trim_end_matches('\0');
+    Ok(path.to_string())
+}
+
+/// Reads the `__fixups__` node (if any) into `(label, triplets)` pairs.
+fn base_reader_fixups(reader: &FdtReader) -> Result<Vec<(String, Vec<String>)>> {
+    let props = reader.properties_of("/__fixups__")?;
+    let mut fixups = Vec::with_capacity(props.len());
+    for (label, value) in props {
+        let triplets = value
+            .split(|&b| b == 0)
+            .filter(|chunk| !chunk.is_empty())
+            .map(|chunk| {
+                std::str::from_utf8(chunk)
+                    .map(str::to_string)
+                    .map_err(|_| Error::BadToken)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        fixups.push((label.to_string(), triplets));
+    }
+    Ok(fixups)
+}
+
+fn parse_triplet(triplet: &str) -> Result<(String, &str, usize)> {
+    let mut parts = triplet.rsplitn(3, ':');
+    let offset = parts.next().ok_or(Error::BadToken)?;
+    let property = parts.next().ok_or(Error::BadToken)?;
+    let path = parts.next().ok_or(Error::BadToken)?;
+    let offset: usize = offset.parse().map_err(|_| Error::BadToken)?;
+    Ok((path.to_string(), property, offset))
+}
+
+/// Walks `data`'s structure block looking for `path`/`property`, returning
+/// the absolute byte offset (within `data`) where its value begins and its
+/// declared length, both validated to fall within `data`.
+fn find_property_value_offset(data: &[u8], path: &str, property: &str) -> Result<(usize, usize)> {
+    let header = parse_header(data)?;
+    let struct_end = header.off_dt_struct + header.size_dt_struct;
+    let strings_end = header.off_dt_strings + header.size_dt_strings;
+    let strings = &data[header.off_dt_strings..strings_end];
+
+    let mut components: Vec<&str> = Vec::new();
+    let mut cursor = header.off_dt_struct;
+
+    while cursor < struct_end {
+        let token = read_u32(data, cursor)?;
+        cursor += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let (name, next) = read_cstr(data, cursor)?;
+                cursor = align4(next);
+                components.push(name);
+            }
+            FDT_END_NODE => {
+                components.pop();
+            }
+            FDT_PROP => {
+                let len = read_u32(data, cursor)? as usize;
+                let nameoff = read_u32(data, cursor + 4)? as usize;
+                let value_offset = cursor + 8;
+                data.get(value_offset..value_offset + len)
+                    .ok_or(Error::Truncated)?;
+                cursor = align4(value_offset + len);
+
+                let (name, _) = read_cstr(strings, nameoff)?;
+                let current_path = if components.len() <= 1 {
+                    "/".to_string()
+                } else {
+                    format!("/{}", components[1..].join("/"))
+                };
+                if name == property && current_path == path {
+                    return Ok((value_offset, len));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Err(Error::NotFound)
+}
+
+/// Returns the full paths of `parent`'s direct children, in document order.
+fn direct_children<'a>(reader: &'a FdtReader, parent: &str) -> Vec<&'a str> {
+    let prefix = if parent == "/" {
+        "/".to_string()
+    } else {
+        format!("{parent}/")
+    };
+    reader
+        .nodes()
+        .filter(|path| {
+            path.strip_prefix(&prefix)
+                .is_some_and(|rest| !rest.is_empty() && !rest.contains('/'))
+        })
+        .filter(|path| !is_housekeeping(path))
+        .collect()
+}
+
+fn is_housekeeping(path: &str) -> bool {
+    matches!(path, "/__symbols__" | "/__fixups__" | "/__local_fixups__")
+}
+
+fn copy_subtree(writer: &mut FdtWriter, reader: &FdtReader, path: &str) -> Result<()> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let node = writer.begin_node(name)?;
+
+    for (prop_name, value) in reader.properties_of(path)? {
+        if *prop_name == "phandle" {
+            let phandle_bytes: [u8; 4] = (*value).try_into().map_err(|_| Error::BadToken)?;
+            writer.property_phandle(u32::from_be_bytes(phandle_bytes))?;
+        } else {
+            writer.property(prop_name, value)?;
+        }
+    }
+
+    for child in direct_children(reader, path) {
+        copy_subtree(writer, reader, child)?;
+    }
+
+    writer.end_node(node)
+}
@@ -0,0 +1,172 @@
+use crate::header::{parse_header, read_cstr, read_u32};
+use crate::{Error, Result, FDT_BEGIN_NODE, FDT_END, FDT_END_NODE, FDT_NOP, FDT_PROP};
+
+struct ParsedNode<'a> {
+    path: String,
+    properties: Vec<(&'a str, &'a [u8])>,
+}
+
+/// Parses and queries a flattened device tree blob produced by
+/// [`FdtWriter::finish`](crate::FdtWriter::finish) (or any spec-conformant DTB).
+///
+/// The whole structure block is walked once, up front, into a flat list of
+/// nodes addressed by their full path (e.g. `/memory@80000000/bank0`); lookups
+/// afterwards are simple linear scans, mirroring the querying done against
+/// libfdt in integration tests.
+pub struct FdtReader<'a> {
+    nodes: Vec<ParsedNode<'a>>,
+}
+
+impl<'a> FdtReader<'a> {
+    /// Validates the 40-byte header of `data` and walks the structure block,
+    /// building an index of every node and property in the blob.
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        let header = parse_header(data)?;
+        let struct_end = header.off_dt_struct + header.size_dt_struct;
+        let strings_end = header.off_dt_strings + header.size_dt_strings;
+
+        let strings = &data[header.off_dt_strings..strings_end];
+        let nodes = parse_struct_block(&data[header.off_dt_struct..struct_end], strings)?;
+
+        Ok(FdtReader { nodes })
+    }
+
+    /// Returns the full paths of every node in the tree, in depth-first
+    /// (document) order.
+    pub fn nodes(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().map(|n| n.path.as_str())
+    }
+
+    /// Looks up a property by full node path (e.g. `/memory@80000000`) and
+    /// property name, returning its raw value bytes.
+    pub fn get_property(&self, path: &str, name: &str) -> Result<&'a [u8]> {
+        self.properties_of(path)?
+            .iter()
+            .find(|(prop_name, _)| *prop_name == name)
+            .map(|(_, value)| *value)
+            .ok_or(Error::NotFound)
+    }
+
+    /// Returns all properties of the node at `path` as `(name, value)` pairs.
+    pub fn properties_of(&self, path: &str) -> Result<&[(&'a str, &'a [u8])]> {
+        self.nodes
+            .iter()
+            .find(|n| n.path == path)
+            .map(|n| n.properties.as_slice())
+            .ok_or(Error::NotFound)
+    }
+
+    /// Enumerates `(address, size)` for every `reg` range under a node whose
+    /// `device_type` is `"memory"`, assuming 64-bit (two-cell) addresses and
+    /// sizes, as written by [`FdtWriter`](crate::FdtWriter) property helpers.
+    pub fn memory_ranges(&self) -> Result<Vec<(u64, u64)>> {
+        let mut ranges = Vec::new();
+        for node in &self.nodes {
+            let is_memory = node
+                .properties
+                .iter()
+                .any(|(name, value)| *name == "device_type" && is_cstr(value, "memory"));
+            if !is_memory {
+                continue;
+            }
+            if let Some((_, reg)) = node.properties.iter().find(|(name, _)| *name == "reg") {
+                for chunk in reg.chunks_exact(16) {
+                    let address = u64::from_be_bytes(chunk[0..8].try_into().unwrap());
+                    let size = u64::from_be_bytes(chunk[8..16].try_into().unwrap());
+                    ranges.push((address, size));
+                }
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// Resolves a `phandle` value to the full path of the node that declares
+    /// it via a `phandle` property.
+    pub fn node_by_phandle(&self, phandle: u32) -> Result<&str> {
+        let wanted = phandle.to_be_bytes();
+        self.nodes
+            .iter()
+            .find(|n| {
+                n.properties
+                    .iter()
+                    .any(|(name, value)| *name == "phandle" && *value == wanted)
+            })
+            .map(|n| n.path.as_str())
+            .ok_or(Error::NotFound)
+    }
+}
+
+fn is_cstr(value: &[u8], expected: &str) -> bool {
+    value
+        .split_last()
+        .map(|(&last, rest)| last == 0 && rest == expected.as_bytes())
+        .unwrap_or(false)
+}
+
+fn parse_struct_block<'a>(
+    struct_block: &'a [u8],
+    strings: &'a [u8],
+) -> Result<Vec<ParsedNode<'a>>> {
+    let mut nodes: Vec<ParsedNode<'a>> = Vec::new();
+    let mut open_node_indices: Vec<usize> = Vec::new();
+    let mut path_components: Vec<&'a str> = Vec::new();
+    let mut cursor = 0usize;
+    let mut seen_end = false;
+
+    while cursor < struct_block.len() {
+        let token = read_u32(struct_block, cursor)?;
+        cursor += 4;
+
+        match token {
+            FDT_BEGIN_NODE => {
+                let (name, next) = read_cstr(struct_block, cursor)?;
+                cursor = crate::align4(next);
+
+                path_components.push(name);
+                let path = if path_components.len() <= 1 {
+                    "/".to_string()
+                } else {
+                    format!("/{}", path_components[1..].join("/"))
+                };
+                nodes.push(ParsedNode {
+                    path,
+                    properties: Vec::new(),
+                });
+                open_node_indices.push(nodes.len() - 1);
+            }
+            FDT_END_NODE => {
+                if open_node_indices.pop().is_none() {
+                    return Err(Error::BadToken);
+                }
+                path_components.pop();
+            }
+            FDT_PROP => {
+                let len = read_u32(struct_block, cursor)? as usize;
+                let nameoff = read_u32(struct_block, cursor + 4)? as usize;
+                cursor += 8;
+
+                let value = struct_block
+                    .get(cursor..cursor + len)
+                    .ok_or(Error::Truncated)?;
+                cursor = crate::align4(cursor + len);
+
+                let (name, _) = read_cstr(strings, nameoff)?;
+
+                let &node_index = open_node_indices.last().ok_or(Error::BadToken)?;
+                nodes[node_index].properties.push((name, value));
+            }
+            FDT_NOP => {}
+            FDT_END => {
+                seen_end = true;
+                break;
+            }
+            _ => return Err(Error::BadToken),
+        }
+    }
+
+    if !seen_end || !open_node_indices.is_empty() {
+        return Err(Error::BadToken);
+    }
+
+    Ok(nodes)
+}
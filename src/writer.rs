@@ -0,0 +1,943 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+
+use crate::header::{parse_header, read_cstr, read_u32};
+use crate::{
+    align4, Error, Result, FDT_BEGIN_NODE, FDT_END, FDT_END_NODE, FDT_HEADER_SIZE,
+    FDT_LAST_COMP_VERSION, FDT_MAGIC, FDT_NOP, FDT_PROP, FDT_VERSION,
+};
+
+/// A single entry in the memory reservation block (`/memreserve/`).
+///
+/// These mark physical memory ranges (e.g. firmware-reserved regions) that the
+/// guest OS must not use, independent of anything described by the node tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FdtReserveEntry {
+    /// Start address of the reserved region.
+    pub address: u64,
+    /// Size in bytes of the reserved region.
+    pub size: u64,
+}
+
+impl FdtReserveEntry {
+    /// Creates a new reservation entry covering `[address, address + size)`.
+    pub fn new(address: u64, size: u64) -> Result<Self> {
+        Ok(FdtReserveEntry { address, size })
+    }
+
+    fn to_be_bytes(self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[0..8].copy_from_slice(&self.address.to_be_bytes());
+        bytes[8..16].copy_from_slice(&self.size.to_be_bytes());
+        bytes
+    }
+}
+
+/// A handle to a node opened with [`FdtWriter::begin_node`].
+///
+/// Must be passed back to [`FdtWriter::end_node`] to close the node. Handles
+/// are not interchangeable between nodes: closing anything other than the
+/// innermost open node is rejected with [`Error::OutOfOrderEndNode`].
+#[derive(Debug)]
+pub struct FdtWriterNode {
+    id: u64,
+}
+
+/// A pending `property_phandle_ref_label` that could not be resolved to a
+/// phandle value when it was recorded, and must be settled (or exported for
+/// an overlay consumer) at `finish` time.
+struct FixupEntry {
+    label: String,
+    path: String,
+    property: String,
+    /// Byte offset into `FdtWriter::data` of the placeholder `u32` cell.
+    data_offset: usize,
+}
+
+/// Incrementally builds a flattened device tree blob.
+///
+/// Nodes are opened with [`begin_node`](FdtWriter::begin_node) and closed in
+/// LIFO order with [`end_node`](FdtWriter::end_node); properties may only be
+/// added while at least one node is open. Call [`finish`](FdtWriter::finish)
+/// once the tree is complete to serialize it to a `Vec<u8>`.
+pub struct FdtWriter {
+    data: Vec<u8>,
+    strings: Vec<u8>,
+    string_offsets: Vec<(String, u32)>,
+    mem_reservations: Vec<FdtReserveEntry>,
+    boot_cpuid_phys: u32,
+    node_stack: Vec<u64>,
+    next_node_id: u64,
+    node_opened: bool,
+    node_closed: bool,
+    phandles: HashSet<u32>,
+    allocated_phandles: HashSet<u32>,
+    next_phandle: u32,
+    path_stack: Vec<String>,
+    /// Labels attached with [`FdtWriter::label_node`], as `(label, path)`.
+    labels: Vec<(String, String)>,
+    /// Phandle already written for a given node path, so same-tree
+    /// `property_phandle_ref_label` references can be resolved immediately.
+    phandle_by_path: HashMap<String, u32>,
+    fixups: Vec<FixupEntry>,
+    extra_materialized: bool,
+}
+
+impl FdtWriter {
+    /// Creates a new, empty writer with no memory reservations.
+    pub fn new() -> Result<Self> {
+        Self::new_with_mem_reserv(&[])
+    }
+
+    /// Creates a new, empty writer with the given memory reservation entries.
+    pub fn new_with_mem_reserv(mem_reservations: &[FdtReserveEntry]) -> Result<Self> {
+        Ok(FdtWriter {
+            data: Vec::new(),
+            strings: Vec::new(),
+            string_offsets: Vec::new(),
+            mem_reservations: mem_reservations.to_vec(),
+            boot_cpuid_phys: 0,
+            node_stack: Vec::new(),
+            next_node_id: 0,
+            node_opened: false,
+            node_closed: false,
+            phandles: HashSet::new(),
+            allocated_phandles: HashSet::new(),
+            next_phandle: 0,
+            path_stack: Vec::new(),
+            labels: Vec::new(),
+            phandle_by_path: HashMap::new(),
+            fixups: Vec::new(),
+            extra_materialized: false,
+        })
+    }
+
+    /// Reopens a finished blob for appending new content, porting the
+    /// `fdt_open_into` + `fdt_pack` workflow from libfdt.
+    ///
+    /// The blob's struct block, strings (with their dedup map), memory
+    /// reservations and already-used phandles are reconstructed, and the
+    /// root node is left open so new top-level children can be added with
+    /// `begin_node`/`property_*`/`end_node` before calling `finish` again.
+    /// The returned [`FdtWriterNode`] is the reopened root and must be
+    /// passed to `end_node` once appending is done.
+    pub fn from_blob(data: &[u8]) -> Result<(Self, FdtWriterNode)> {
+        let header = parse_header(data)?;
+        let struct_bytes =
+            &data[header.off_dt_struct..header.off_dt_struct + header.size_dt_struct];
+        let strings = &data[header.off_dt_strings..header.off_dt_strings + header.size_dt_strings];
+
+        if struct_bytes.len() < 4 || read_u32(struct_bytes, struct_bytes.len() - 4)? != FDT_END {
+            return Err(Error::BadToken);
+        }
+        let body = &struct_bytes[..struct_bytes.len() - 4];
+
+        let (node_count, phandles) = scan_body(body, strings)?;
+        if node_count == 0 || body.len() < 4 || read_u32(body, body.len() - 4)? != FDT_END_NODE {
+            return Err(Error::BadToken);
+        }
+        let reopened_body = &body[..body.len() - 4];
+
+        let mut mem_reservations = Vec::new();
+        let mut offset = header.off_mem_rsvmap;
+        loop {
+            let address = u64::from_be_bytes(
+                data.get(offset..offset + 8)
+                    .ok_or(Error::Truncated)?
+                    .try_into()
+                    .unwrap(),
+            );
+            let size = u64::from_be_bytes(
+                data.get(offset + 8..offset + 16)
+                    .ok_or(Error::Truncated)?
+                    .try_into()
+                    .unwrap(),
+            );
+            offset += 16;
+            if address == 0 && size == 0 {
+                break;
+            }
+            mem_reservations.push(FdtReserveEntry { address, size });
+        }
+
+        let mut string_offsets = Vec::new();
+        let mut cursor = 0;
+        while cursor < strings.len() {
+            let (name, next) = read_cstr(strings, cursor)?;
+            string_offsets.push((name.to_string(), cursor as u32));
+            cursor = next;
+        }
+
+        let next_phandle = phandles.iter().copied().max().unwrap_or(0);
+        let allocated_phandles = phandles.clone();
+
+        let writer = FdtWriter {
+            data: reopened_body.to_vec(),
+            strings: strings.to_vec(),
+            string_offsets,
+            mem_reservations,
+            boot_cpuid_phys: header.boot_cpuid_phys,
+            node_stack: vec![0],
+            next_node_id: node_count,
+            node_opened: true,
+            node_closed: false,
+            phandles,
+            allocated_phandles,
+            next_phandle,
+            path_stack: vec![String::new()],
+            labels: Vec::new(),
+            phandle_by_path: HashMap::new(),
+            fixups: Vec::new(),
+            extra_materialized: false,
+        };
+
+        Ok((writer, FdtWriterNode { id: 0 }))
+    }
+
+    /// Sets the `boot_cpuid_phys` header field (the physical CPU ID the
+    /// guest should boot on). Defaults to 0.
+    pub fn set_boot_cpuid_phys(&mut self, boot_cpuid_phys: u32) {
+        self.boot_cpuid_phys = boot_cpuid_phys;
+    }
+
+    /// Opens a new node named `name` as a child of the currently open node
+    /// (or as the root node, if none is open yet).
+    ///
+    /// `name` must not contain `/`; an empty name is only meaningful for the
+    /// root node.
+    pub fn begin_node(&mut self, name: &str) -> Result<FdtWriterNode> {
+        if name.contains('/') {
+            return Err(Error::InvalidNodeName);
+        }
+
+        self.push_u32(FDT_BEGIN_NODE);
+        self.push_bytes(name.as_bytes());
+        self.push_bytes(&[0u8]);
+        self.pad_data();
+
+        let id = self.next_node_id;
+        self.next_node_id += 1;
+        self.node_stack.push(id);
+        self.path_stack.push(name.to_string());
+        self.node_opened = true;
+
+        Ok(FdtWriterNode { id })
+    }
+
+    /// Closes the node previously opened by `begin_node`, which must be the
+    /// innermost open node.
+    pub fn end_node(&mut self, node: FdtWriterNode) -> Result<()> {
+        if self.node_stack.last() != Some(&node.id) {
+            return Err(Error::OutOfOrderEndNode);
+        }
+        self.node_stack.pop();
+        self.path_stack.pop();
+        self.push_u32(FDT_END_NODE);
+        if self.node_stack.is_empty() {
+            self.node_closed = true;
+        }
+        Ok(())
+    }
+
+    /// Opens a node named `name`, runs `f` against a [`NodeBuilder`] scoped to
+    /// it, then closes the node — regardless of whether `f` succeeds.
+    ///
+    /// Unlike the flat [`begin_node`](Self::begin_node)/[`end_node`](Self::end_node)
+    /// pair, the node handle here never escapes past the closure, so
+    /// [`Error::UnclosedNode`] and [`Error::OutOfOrderEndNode`] can't happen
+    /// on this path: every node opened by `node` (or [`NodeBuilder::child`])
+    /// is closed in LIFO order by construction. Use this when the tree shape
+    /// is known statically; fall back to `begin_node`/`end_node` for trees
+    /// built up dynamically (e.g. from a runtime-sized list of devices).
+    pub fn node(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(&mut NodeBuilder) -> Result<()>,
+    ) -> Result<()> {
+        let handle = self.begin_node(name)?;
+        let mut builder = NodeBuilder {
+            writer: self,
+            node: Some(handle),
+        };
+        let result = f(&mut builder);
+        let handle = builder
+            .node
+            .take()
+            .expect("NodeBuilder always holds its node until closed");
+        let close_result = builder.writer.end_node(handle);
+        result.and(close_result)
+    }
+
+    /// Attaches a symbolic `label` to `node`, which must still be open.
+    ///
+    /// Labels are collected into a generated `__symbols__` node (mapping
+    /// label to full node path) when `finish` is called, and can be the
+    /// target of [`property_phandle_ref_label`](Self::property_phandle_ref_label)
+    /// calls elsewhere in the tree (or, via [`crate::apply_overlay`], in a
+    /// separate overlay blob).
+    ///
+    /// Fails with [`Error::DuplicateLabel`] if `label` is already attached
+    /// to a node, including `node` itself.
+    pub fn label_node(&mut self, node: &FdtWriterNode, label: &str) -> Result<()> {
+        if self.labels.iter().any(|(existing, _)| existing == label) {
+            return Err(Error::DuplicateLabel);
+        }
+        let idx = self
+            .node_stack
+            .iter()
+            .position(|&id| id == node.id)
+            .ok_or(Error::NotFound)?;
+        let path = self.path_at(idx);
+        self.labels.push((label.to_string(), path));
+        Ok(())
+    }
+
+    fn current_path(&self) -> String {
+        self.path_at(self.path_stack.len().saturating_sub(1))
+    }
+
+    fn path_at(&self, depth: usize) -> String {
+        if depth == 0 {
+            "/".to_string()
+        } else {
+            format!("/{}", self.path_stack[1..=depth].join("/"))
+        }
+    }
+
+    /// Adds a raw-bytes property to the currently open node.
+    pub fn property(&mut self, name: &str, val: &[u8]) -> Result<()> {
+        self.property_raw(name, val)?;
+        Ok(())
+    }
+
+    /// Writes a property and returns the byte offset into `self.data` where
+    /// its value landed, so callers that need to patch it later (phandle
+    /// fixups) can find it again without re-parsing the stream.
+    fn property_raw(&mut self, name: &str, val: &[u8]) -> Result<usize> {
+        self.check_property_allowed()?;
+
+        let nameoff = self.intern_string(name);
+        self.push_u32(FDT_PROP);
+        self.push_u32(val.len() as u32);
+        self.push_u32(nameoff);
+        let value_offset = self.data.len();
+        self.push_bytes(val);
+        self.pad_data();
+
+        Ok(value_offset)
+    }
+
+    /// Adds a zero-length (`bool`-like) property.
+    pub fn property_null(&mut self, name: &str) -> Result<()> {
+        self.property(name, &[])
+    }
+
+    /// Adds a NUL-terminated string property.
+    pub fn property_string(&mut self, name: &str, val: &str) -> Result<()> {
+        let cstr = CString::new(val).map_err(|_| Error::InvalidString)?;
+        self.property(name, cstr.as_bytes_with_nul())
+    }
+
+    /// Adds a property holding a list of NUL-terminated strings back to back.
+    pub fn property_string_list(&mut self, name: &str, values: Vec<String>) -> Result<()> {
+        let mut bytes = Vec::new();
+        for value in values {
+            let cstr = CString::new(value).map_err(|_| Error::InvalidString)?;
+            bytes.extend_from_slice(cstr.as_bytes_with_nul());
+        }
+        self.property(name, &bytes)
+    }
+
+    /// Adds a single big-endian `u32` cell property.
+    pub fn property_u32(&mut self, name: &str, val: u32) -> Result<()> {
+        self.property(name, &val.to_be_bytes())
+    }
+
+    /// Adds a single big-endian `u64` (two-cell) property.
+    pub fn property_u64(&mut self, name: &str, val: u64) -> Result<()> {
+        self.property(name, &val.to_be_bytes())
+    }
+
+    /// Adds a property holding an array of big-endian `u32` cells.
+    pub fn property_array_u32(&mut self, name: &str, vals: &[u32]) -> Result<()> {
+        let mut bytes = Vec::with_capacity(vals.len() * 4);
+        for val in vals {
+            bytes.extend_from_slice(&val.to_be_bytes());
+        }
+        self.property(name, &bytes)
+    }
+
+    /// Adds a property holding an array of big-endian `u64` cells.
+    pub fn property_array_u64(&mut self, name: &str, vals: &[u64]) -> Result<()> {
+        let mut bytes = Vec::with_capacity(vals.len() * 8);
+        for val in vals {
+            bytes.extend_from_slice(&val.to_be_bytes());
+        }
+        self.property(name, &bytes)
+    }
+
+    /// Adds a `phandle` property with the given value to the currently open
+    /// node, failing if that value has already been used elsewhere in the
+    /// tree.
+    pub fn property_phandle(&mut self, phandle: u32) -> Result<()> {
+        if !self.phandles.insert(phandle) {
+            return Err(Error::DuplicatePhandle);
+        }
+        self.next_phandle = self.next_phandle.max(phandle);
+        let path = self.current_path();
+        self.property_u32("phandle", phandle)?;
+        self.phandle_by_path.insert(path, phandle);
+        Ok(())
+    }
+
+    /// Allocates a guaranteed-unique `phandle` value and emits it as the
+    /// `phandle` property on the currently open node, for wiring up
+    /// cross-references (interrupt parents, clock providers, ...) without
+    /// the caller having to pick integers by hand.
+    ///
+    /// Fails with [`Error::PhandleSpaceExhausted`] if `u32::MAX` is already
+    /// in use (e.g. via `property_phandle` or a reopened blob whose highest
+    /// existing phandle is already the maximum value).
+    pub fn alloc_phandle(&mut self) -> Result<u32> {
+        let phandle = self
+            .next_phandle
+            .checked_add(1)
+            .ok_or(Error::PhandleSpaceExhausted)?;
+        self.property_phandle(phandle)?;
+        self.next_phandle = phandle;
+        self.allocated_phandles.insert(phandle);
+        Ok(phandle)
+    }
+
+    /// Adds a property holding a single `u32` cell that references a
+    /// `phandle` previously returned by [`alloc_phandle`](Self::alloc_phandle),
+    /// failing with [`Error::UnknownPhandle`] if it was never allocated.
+    pub fn property_phandle_ref(&mut self, name: &str, phandle: u32) -> Result<()> {
+        if !self.allocated_phandles.contains(&phandle) {
+            return Err(Error::UnknownPhandle);
+        }
+        self.property_u32(name, phandle)
+    }
+
+    /// Adds a property holding a single `u32` cell that references the
+    /// phandle of whatever node is (or will be) labeled `label` with
+    /// [`label_node`](Self::label_node), rather than a phandle value the
+    /// caller already has in hand.
+    ///
+    /// If `label` is already attached to a node in this tree that has a
+    /// known phandle, the cell is written immediately. Otherwise the
+    /// reference is recorded as a fixup and resolved at `finish` time: a
+    /// same-tree label whose phandle becomes known before `finish` is
+    /// patched in place; anything still unresolved is exported as a
+    /// `__local_fixups__` entry (a same-tree label whose node never got a
+    /// phandle, which [`crate::apply_overlay`] rejects with
+    /// [`Error::UnresolvedFixup`]) or a `__fixups__` entry (a label expected
+    /// to come from a base tree via [`crate::apply_overlay`]), mirroring the
+    /// `dtc`/`fdtoverlay` overlay format.
+    pub fn property_phandle_ref_label(&mut self, name: &str, label: &str) -> Result<()> {
+        let known = self
+            .labels
+            .iter()
+            .find(|(existing, _)| existing == label)
+            .and_then(|(_, path)| self.phandle_by_path.get(path).copied());
+        if let Some(phandle) = known {
+            return self.property_u32(name, phandle);
+        }
+
+        let path = self.current_path();
+        let data_offset = self.property_raw(name, &0u32.to_be_bytes())?;
+        self.fixups.push(FixupEntry {
+            label: label.to_string(),
+            path,
+            property: name.to_string(),
+            data_offset,
+        });
+        Ok(())
+    }
+
+    /// Resolves pending `label_node`/`property_phandle_ref_label` state into
+    /// the struct block: patches same-tree references whose phandle is
+    /// already known, and emits `__symbols__`, `__local_fixups__` and
+    /// `__fixups__` housekeeping nodes as children of the root for whatever
+    /// is left. Runs once; safe to call repeatedly (e.g. from both
+    /// `finish` and `finish_into`).
+    fn materialize_extra_nodes(&mut self) -> Result<()> {
+        if !self.node_stack.is_empty() {
+            return Err(Error::UnclosedNode);
+        }
+        if self.extra_materialized {
+            return Ok(());
+        }
+        self.extra_materialized = true;
+
+        if !self.labels.is_empty() {
+            self.inject_symbols_node()?;
+        }
+
+        if !self.fixups.is_empty() {
+            let fixups = std::mem::take(&mut self.fixups);
+            let mut external = Vec::new();
+            let mut local = Vec::new();
+            for fixup in fixups {
+                let label_path = self
+                    .labels
+                    .iter()
+                    .find(|(label, _)| *label == fixup.label)
+                    .map(|(_, path)| path.clone());
+                match label_path {
+                    Some(path) => match self.phandle_by_path.get(&path) {
+                        Some(&phandle) => {
+                            self.data[fixup.data_offset..fixup.data_offset + 4]
+                                .copy_from_slice(&phandle.to_be_bytes());
+                        }
+                        None => local.push(fixup),
+                    },
+                    None => external.push(fixup),
+                }
+            }
+            if !external.is_empty() {
+                self.inject_fixups_node(&external)?;
+            }
+            if !local.is_empty() {
+                self.inject_local_fixups_node(&local)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Splices `extra` (a complete, balanced run of node/property tokens)
+    /// in as the last child of the root node, just before its closing
+    /// `FDT_END_NODE` token.
+    fn splice_before_root_end(&mut self, extra: Vec<u8>) {
+        let insert_at = self.data.len() - 4;
+        self.data.splice(insert_at..insert_at, extra);
+    }
+
+    fn inject_symbols_node(&mut self) -> Result<()> {
+        let labels = self.labels.clone();
+        let mut extra = Vec::new();
+        push_u32_into(&mut extra, FDT_BEGIN_NODE);
+        push_bytes_into(&mut extra, b"__symbols__\0");
+        pad_into(&mut extra);
+
+        for (label, path) in labels {
+            let nameoff = self.intern_string(&label);
+            let cstr = CString::new(path).map_err(|_| Error::InvalidString)?;
+            let value = cstr.as_bytes_with_nul();
+            push_u32_into(&mut extra, FDT_PROP);
+            push_u32_into(&mut extra, value.len() as u32);
+            push_u32_into(&mut extra, nameoff);
+            push_bytes_into(&mut extra, value);
+            pad_into(&mut extra);
+        }
+
+        push_u32_into(&mut extra, FDT_END_NODE);
+        self.splice_before_root_end(extra);
+        Ok(())
+    }
+
+    /// Emits a `__fixups__` node: one string-list property per referenced
+    /// label, each value a list of `path:property:offset` triplets
+    /// identifying every cell that must be patched with that label's
+    /// phandle once resolved against a base tree's `__symbols__`.
+    fn inject_fixups_node(&mut self, fixups: &[FixupEntry]) -> Result<()> {
+        let mut by_label: Vec<(String, Vec<String>)> = Vec::new();
+        for fixup in fixups {
+            let triplet = format!("{}:{}:{}", fixup.path, fixup.property, 0);
+            match by_label.iter_mut().find(|(label, _)| *label == fixup.label) {
+                Some((_, triplets)) => triplets.push(triplet),
+                None => by_label.push((fixup.label.clone(), vec![triplet])),
+            }
+        }
+
+        let mut extra = Vec::new();
+        push_u32_into(&mut extra, FDT_BEGIN_NODE);
+        push_bytes_into(&mut extra, b"__fixups__\0");
+        pad_into(&mut extra);
+
+        for (label, triplets) in by_label {
+            let nameoff = self.intern_string(&label);
+            let mut value = Vec::new();
+            for triplet in triplets {
+                let cstr = CString::new(triplet).map_err(|_| Error::InvalidString)?;
+                value.extend_from_slice(cstr.as_bytes_with_nul());
+            }
+            push_u32_into(&mut extra, FDT_PROP);
+            push_u32_into(&mut extra, value.len() as u32);
+            push_u32_into(&mut extra, nameoff);
+            push_bytes_into(&mut extra, &value);
+            pad_into(&mut extra);
+        }
+
+        push_u32_into(&mut extra, FDT_END_NODE);
+        self.splice_before_root_end(extra);
+        Ok(())
+    }
+
+    /// Emits a `__local_fixups__` node: nested nodes mirroring the path of
+    /// every not-yet-resolvable same-tree reference, with a property at
+    /// each leaf (named like the original property) holding the byte
+    /// offsets within it that still need to be patched with a phandle.
+    fn inject_local_fixups_node(&mut self, fixups: &[FixupEntry]) -> Result<()> {
+        let mut root = FixupTreeNode::default();
+        for fixup in fixups {
+            let mut node = &mut root;
+            for component in fixup.path.split('/').filter(|c| !c.is_empty()) {
+                node = node.children.entry(component.to_string()).or_default();
+            }
+            match node
+                .props
+                .iter_mut()
+                .find(|(name, _)| *name == fixup.property)
+            {
+                Some((_, offsets)) => offsets.push(0),
+                None => node.props.push((fixup.property.clone(), vec![0])),
+            }
+        }
+
+        let mut extra = Vec::new();
+        push_u32_into(&mut extra, FDT_BEGIN_NODE);
+        push_bytes_into(&mut extra, b"__local_fixups__\0");
+        pad_into(&mut extra);
+        self.emit_fixup_tree(&root, &mut extra)?;
+        push_u32_into(&mut extra, FDT_END_NODE);
+        self.splice_before_root_end(extra);
+        Ok(())
+    }
+
+    fn emit_fixup_tree(&mut self, node: &FixupTreeNode, extra: &mut Vec<u8>) -> Result<()> {
+        for (name, offsets) in &node.props {
+            let nameoff = self.intern_string(name);
+            let mut value = Vec::with_capacity(offsets.len() * 4);
+            for offset in offsets {
+                value.extend_from_slice(&offset.to_be_bytes());
+            }
+            push_u32_into(extra, FDT_PROP);
+            push_u32_into(extra, value.len() as u32);
+            push_u32_into(extra, nameoff);
+            push_bytes_into(extra, &value);
+            pad_into(extra);
+        }
+
+        for (name, child) in &node.children {
+            push_u32_into(extra, FDT_BEGIN_NODE);
+            push_bytes_into(extra, name.as_bytes());
+            push_bytes_into(extra, &[0u8]);
+            pad_into(extra);
+            self.emit_fixup_tree(child, extra)?;
+            push_u32_into(extra, FDT_END_NODE);
+        }
+        Ok(())
+    }
+
+    fn check_property_allowed(&self) -> Result<()> {
+        if self.node_stack.is_empty() {
+            if self.node_closed {
+                return Err(Error::PropertyAfterEndNode);
+            }
+            if !self.node_opened {
+                return Err(Error::PropertyBeforeBeginNode);
+            }
+        }
+        Ok(())
+    }
+
+    fn intern_string(&mut self, name: &str) -> u32 {
+        if let Some((_, offset)) = self.string_offsets.iter().find(|(s, _)| s == name) {
+            return *offset;
+        }
+        let offset = self.strings.len() as u32;
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        self.string_offsets.push((name.to_string(), offset));
+        offset
+    }
+
+    fn push_u32(&mut self, val: u32) {
+        self.data.extend_from_slice(&val.to_be_bytes());
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn pad_data(&mut self) {
+        let padded_len = align4(self.data.len());
+        self.data.resize(padded_len, 0);
+    }
+
+    /// Computes the total serialized size of the blob, were it finished now.
+    fn total_size(&self) -> Result<usize> {
+        let mem_rsvmap_size = (self.mem_reservations.len() + 1) * 16;
+        let struct_size = self.data.len() + 4; // + FDT_END token
+        let size = FDT_HEADER_SIZE + mem_rsvmap_size + struct_size + self.strings.len();
+        if u32::try_from(size).is_err() {
+            return Err(Error::TotalSizeTooLarge);
+        }
+        Ok(size)
+    }
+
+    /// Serializes the finished blob into `buf`, which must be at least
+    /// [`total_size`](FdtWriter::total_size) bytes. Returns the number of
+    /// bytes written.
+    fn write_to(&self, buf: &mut [u8]) -> Result<usize> {
+        if !self.node_stack.is_empty() {
+            return Err(Error::UnclosedNode);
+        }
+
+        let total_size = self.total_size()?;
+        if buf.len() < total_size {
+            return Err(Error::TotalSizeTooLarge);
+        }
+
+        let mem_rsvmap_size = (self.mem_reservations.len() + 1) * 16;
+        let off_mem_rsvmap = FDT_HEADER_SIZE;
+        let off_dt_struct = off_mem_rsvmap + mem_rsvmap_size;
+        let size_dt_struct = (self.data.len() + 4) as u32;
+        let off_dt_strings = off_dt_struct + size_dt_struct as usize;
+        let size_dt_strings = self.strings.len() as u32;
+
+        buf[0..4].copy_from_slice(&FDT_MAGIC.to_be_bytes());
+        buf[4..8].copy_from_slice(&(total_size as u32).to_be_bytes());
+        buf[8..12].copy_from_slice(&(off_dt_struct as u32).to_be_bytes());
+        buf[12..16].copy_from_slice(&(off_dt_strings as u32).to_be_bytes());
+        buf[16..20].copy_from_slice(&(off_mem_rsvmap as u32).to_be_bytes());
+        buf[20..24].copy_from_slice(&FDT_VERSION.to_be_bytes());
+        buf[24..28].copy_from_slice(&FDT_LAST_COMP_VERSION.to_be_bytes());
+        buf[28..32].copy_from_slice(&self.boot_cpuid_phys.to_be_bytes());
+        buf[32..36].copy_from_slice(&size_dt_strings.to_be_bytes());
+        buf[36..40].copy_from_slice(&size_dt_struct.to_be_bytes());
+
+        let mut offset = off_mem_rsvmap;
+        for entry in &self.mem_reservations {
+            buf[offset..offset + 16].copy_from_slice(&entry.to_be_bytes());
+            offset += 16;
+        }
+        buf[offset..offset + 16].copy_from_slice(
+            &FdtReserveEntry {
+                address: 0,
+                size: 0,
+            }
+            .to_be_bytes(),
+        );
+        offset += 16;
+        debug_assert_eq!(offset, off_dt_struct);
+
+        buf[offset..offset + self.data.len()].copy_from_slice(&self.data);
+        offset += self.data.len();
+        buf[offset..offset + 4].copy_from_slice(&FDT_END.to_be_bytes());
+        offset += 4;
+        debug_assert_eq!(offset, off_dt_strings);
+
+        buf[offset..offset + self.strings.len()].copy_from_slice(&self.strings);
+        offset += self.strings.len();
+        debug_assert_eq!(offset, total_size);
+
+        Ok(total_size)
+    }
+
+    /// Consumes the writer and serializes the tree to a freshly allocated
+    /// buffer.
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        self.materialize_extra_nodes()?;
+        let mut buf = vec![0u8; self.total_size()?];
+        self.write_to(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Serializes the tree directly into a caller-provided buffer, avoiding
+    /// the extra allocation `finish` makes for VMMs that place the DT at a
+    /// fixed guest-physical address with a bounded-size region.
+    ///
+    /// Returns the number of bytes written. Fails with
+    /// [`Error::TotalSizeTooLarge`] if the serialized tree (header,
+    /// reservation map, struct and strings blocks) would not fit in `buf`,
+    /// or would overflow the 32-bit `totalsize` header field.
+    pub fn finish_into(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.materialize_extra_nodes()?;
+        self.write_to(buf)
+    }
+}
+
+/// A node opened by [`FdtWriter::node`] (or [`NodeBuilder::child`]), scoped
+/// to the closure it was handed to.
+///
+/// Mirrors the property helpers on [`FdtWriter`] itself; `child` is the only
+/// way to open a further nested node, keeping every node this type can ever
+/// reach closed in LIFO order by construction.
+pub struct NodeBuilder<'a> {
+    writer: &'a mut FdtWriter,
+    node: Option<FdtWriterNode>,
+}
+
+impl<'a> NodeBuilder<'a> {
+    /// Opens a child node named `name`, scoping it to `f` the same way
+    /// [`FdtWriter::node`] does.
+    pub fn child(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(&mut NodeBuilder) -> Result<()>,
+    ) -> Result<()> {
+        self.writer.node(name, f)
+    }
+
+    /// Attaches a symbolic label to this node; see
+    /// [`FdtWriter::label_node`].
+    pub fn label(&mut self, label: &str) -> Result<()> {
+        let node = self
+            .node
+            .as_ref()
+            .expect("NodeBuilder always holds its node until closed");
+        self.writer.label_node(node, label)
+    }
+
+    /// Adds a raw-bytes property to this node; see [`FdtWriter::property`].
+    pub fn property(&mut self, name: &str, val: &[u8]) -> Result<()> {
+        self.writer.property(name, val)
+    }
+
+    /// Adds a zero-length (`bool`-like) property; see
+    /// [`FdtWriter::property_null`].
+    pub fn property_null(&mut self, name: &str) -> Result<()> {
+        self.writer.property_null(name)
+    }
+
+    /// Adds a NUL-terminated string property; see
+    /// [`FdtWriter::property_string`].
+    pub fn property_string(&mut self, name: &str, val: &str) -> Result<()> {
+        self.writer.property_string(name, val)
+    }
+
+    /// Adds a property holding a list of NUL-terminated strings back to
+    /// back; see [`FdtWriter::property_string_list`].
+    pub fn property_string_list(&mut self, name: &str, values: Vec<String>) -> Result<()> {
+        self.writer.property_string_list(name, values)
+    }
+
+    /// Adds a single big-endian `u32` cell property; see
+    /// [`FdtWriter::property_u32`].
+    pub fn property_u32(&mut self, name: &str, val: u32) -> Result<()> {
+        self.writer.property_u32(name, val)
+    }
+
+    /// Adds a single big-endian `u64` (two-cell) property; see
+    /// [`FdtWriter::property_u64`].
+    pub fn property_u64(&mut self, name: &str, val: u64) -> Result<()> {
+        self.writer.property_u64(name, val)
+    }
+
+    /// Adds a property holding an array of big-endian `u32` cells; see
+    /// [`FdtWriter::property_array_u32`].
+    pub fn property_array_u32(&mut self, name: &str, cells: &[u32]) -> Result<()> {
+        self.writer.property_array_u32(name, cells)
+    }
+
+    /// Adds a property holding an array of big-endian `u64` cells; see
+    /// [`FdtWriter::property_array_u64`].
+    pub fn property_array_u64(&mut self, name: &str, cells: &[u64]) -> Result<()> {
+        self.writer.property_array_u64(name, cells)
+    }
+
+    /// Adds a `phandle` property with the given value to this node; see
+    /// [`FdtWriter::property_phandle`].
+    pub fn property_phandle(&mut self, phandle: u32) -> Result<()> {
+        self.writer.property_phandle(phandle)
+    }
+
+    /// Allocates a guaranteed-unique `phandle` value and emits it as the
+    /// `phandle` property on this node; see [`FdtWriter::alloc_phandle`].
+    pub fn alloc_phandle(&mut self) -> Result<u32> {
+        self.writer.alloc_phandle()
+    }
+
+    /// Adds a property holding a single `u32` cell that references a
+    /// phandle previously returned by [`alloc_phandle`](Self::alloc_phandle);
+    /// see [`FdtWriter::property_phandle_ref`].
+    pub fn property_phandle_ref(&mut self, name: &str, phandle: u32) -> Result<()> {
+        self.writer.property_phandle_ref(name, phandle)
+    }
+
+    /// Adds a property holding a single `u32` cell that references the
+    /// phandle of whatever node is (or will be) labeled `label`; see
+    /// [`FdtWriter::property_phandle_ref_label`].
+    pub fn property_phandle_ref_label(&mut self, name: &str, label: &str) -> Result<()> {
+        self.writer.property_phandle_ref_label(name, label)
+    }
+}
+
+fn push_u32_into(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn push_bytes_into(buf: &mut Vec<u8>, value: &[u8]) {
+    buf.extend_from_slice(value);
+}
+
+fn pad_into(buf: &mut Vec<u8>) {
+    let padded = align4(buf.len());
+    buf.resize(padded, 0);
+}
+
+/// A node in the path tree used to build `__local_fixups__`: mirrors the
+/// shape of the nodes it describes, with a property list of `(name,
+/// offsets)` at each level instead of the properties' actual values.
+#[derive(Default)]
+struct FixupTreeNode {
+    children: std::collections::BTreeMap<String, FixupTreeNode>,
+    props: Vec<(String, Vec<u32>)>,
+}
+
+/// Walks a balanced (fully-closed) struct body, validating the token stream
+/// and collecting the count of `FDT_BEGIN_NODE` tokens seen (so a reopened
+/// writer can continue handing out unique node ids) and the set of `phandle`
+/// values already in use.
+fn scan_body(body: &[u8], strings: &[u8]) -> Result<(u64, HashSet<u32>)> {
+    let mut cursor = 0usize;
+    let mut depth: i64 = 0;
+    let mut node_count: u64 = 0;
+    let mut phandles = HashSet::new();
+
+    while cursor < body.len() {
+        let token = read_u32(body, cursor)?;
+        cursor += 4;
+
+        match token {
+            FDT_BEGIN_NODE => {
+                let (_, next) = read_cstr(body, cursor)?;
+                cursor = align4(next);
+                depth += 1;
+                node_count += 1;
+            }
+            FDT_END_NODE => {
+                depth -= 1;
+                if depth < 0 {
+                    return Err(Error::BadToken);
+                }
+            }
+            FDT_PROP => {
+                let len = read_u32(body, cursor)? as usize;
+                let nameoff = read_u32(body, cursor + 4)? as usize;
+                cursor += 8;
+
+                let value = body.get(cursor..cursor + len).ok_or(Error::Truncated)?;
+                cursor = align4(cursor + len);
+
+                let (name, _) = read_cstr(strings, nameoff)?;
+                if name == "phandle" {
+                    if let Ok(bytes) = <[u8; 4]>::try_from(value) {
+                        phandles.insert(u32::from_be_bytes(bytes));
+                    }
+                }
+            }
+            FDT_NOP => {}
+            _ => return Err(Error::BadToken),
+        }
+    }
+
+    if depth != 0 {
+        return Err(Error::BadToken);
+    }
+
+    Ok((node_count, phandles))
+}
@@ -1,5 +1,5 @@
 use std::convert::TryInto;
-use vm_fdt_arceos::{Error, FdtReserveEntry, FdtWriter};
+use vm_fdt_arceos::{apply_overlay, Error, FdtReader, FdtReserveEntry, FdtWriter};
 
 const FDT_MAGIC: u32 = 0xd00dfeed;
 
@@ -176,3 +176,347 @@ fn test_large_property_handling() -> Result<(), Error> {
     verify_header(&blob);
     Ok(())
 }
+
+#[test]
+fn test_reader_round_trip() -> Result<(), Error> {
+    let mut fdt = FdtWriter::new()?;
+
+    let root = fdt.begin_node("")?;
+    fdt.property_u32("#address-cells", 2)?;
+    fdt.property_u32("#size-cells", 2)?;
+
+    let memory = fdt.begin_node("memory@80000000")?;
+    fdt.property_string("device_type", "memory")?;
+    fdt.property_array_u64("reg", &[0x8000_0000, 0x4000_0000])?;
+    fdt.end_node(memory)?;
+
+    let cpu = fdt.begin_node("cpu@0")?;
+    fdt.property_string("device_type", "cpu")?;
+    fdt.property_phandle(1)?;
+    fdt.end_node(cpu)?;
+
+    fdt.end_node(root)?;
+    let blob = fdt.finish()?;
+
+    let reader = FdtReader::new(&blob)?;
+
+    assert_eq!(
+        reader.get_property("/memory@80000000", "device_type")?,
+        b"memory\0"
+    );
+    assert_eq!(reader.memory_ranges()?, vec![(0x8000_0000, 0x4000_0000)]);
+    assert_eq!(reader.node_by_phandle(1)?, "/cpu@0");
+    assert_eq!(
+        reader.get_property("/cpu@0", "missing-prop"),
+        Err(Error::NotFound)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_reader_rejects_bad_blob() {
+    assert_eq!(FdtReader::new(&[0u8; 4]).err(), Some(Error::Truncated));
+    assert_eq!(FdtReader::new(&[0u8; 64]).err(), Some(Error::BadHeader));
+}
+
+#[test]
+fn test_reopen_and_append() -> Result<(), Error> {
+    let mut fdt = FdtWriter::new()?;
+    let root = fdt.begin_node("")?;
+    fdt.property_string("compatible", "linux,dummy-virt")?;
+    let cpu = fdt.begin_node("cpu@0")?;
+    fdt.property_phandle(1)?;
+    fdt.end_node(cpu)?;
+    fdt.end_node(root)?;
+    let base_blob = fdt.finish()?;
+
+    let (mut fdt2, root) = FdtWriter::from_blob(&base_blob)?;
+
+    // Re-adding a phandle already present in the reopened blob must still
+    // be rejected as a duplicate.
+    let dup = fdt2.begin_node("dup@0")?;
+    assert_eq!(fdt2.property_phandle(1), Err(Error::DuplicatePhandle));
+    fdt2.end_node(dup)?;
+
+    let memory = fdt2.begin_node("memory@80000000")?;
+    fdt2.property_string("device_type", "memory")?;
+    fdt2.property_array_u64("reg", &[0x8000_0000, 0x4000_0000])?;
+    fdt2.end_node(memory)?;
+
+    fdt2.end_node(root)?;
+    let merged_blob = fdt2.finish()?;
+    verify_header(&merged_blob);
+
+    let reader = FdtReader::new(&merged_blob)?;
+    assert_eq!(
+        reader.get_property("/cpu@0", "phandle")?,
+        &1u32.to_be_bytes()
+    );
+    assert_eq!(reader.memory_ranges()?, vec![(0x8000_0000, 0x4000_0000)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_finish_into() -> Result<(), Error> {
+    let mut fdt = FdtWriter::new()?;
+    let root = fdt.begin_node("root")?;
+    fdt.property_string("compatible", "linux,dummy-virt")?;
+    fdt.end_node(root)?;
+
+    let expected = fdt.finish()?;
+
+    let mut fdt = FdtWriter::new()?;
+    let root = fdt.begin_node("root")?;
+    fdt.property_string("compatible", "linux,dummy-virt")?;
+    fdt.end_node(root)?;
+
+    let mut buf = vec![0u8; expected.len()];
+    let written = fdt.finish_into(&mut buf)?;
+    assert_eq!(written, expected.len());
+    assert_eq!(buf, expected);
+
+    let mut tiny_buf = vec![0u8; 4];
+    assert_eq!(
+        fdt.finish_into(&mut tiny_buf),
+        Err(Error::TotalSizeTooLarge)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_phandle_alloc_and_ref() -> Result<(), Error> {
+    let mut fdt = FdtWriter::new()?;
+    let root = fdt.begin_node("root")?;
+
+    let intc = fdt.begin_node("interrupt-controller")?;
+    let intc_phandle = fdt.alloc_phandle()?;
+    fdt.end_node(intc)?;
+
+    let dev = fdt.begin_node("uart@1000")?;
+    fdt.property_phandle_ref("interrupt-parent", intc_phandle)?;
+    assert_eq!(
+        fdt.property_phandle_ref("clocks", 0xffff),
+        Err(Error::UnknownPhandle)
+    );
+    fdt.end_node(dev)?;
+
+    fdt.end_node(root)?;
+    let blob = fdt.finish()?;
+    verify_header(&blob);
+
+    Ok(())
+}
+
+#[test]
+fn test_alloc_phandle_exhaustion() -> Result<(), Error> {
+    let mut fdt = FdtWriter::new()?;
+    let root = fdt.begin_node("root")?;
+    fdt.property_phandle(u32::MAX)?;
+    assert_eq!(fdt.alloc_phandle(), Err(Error::PhandleSpaceExhausted));
+    fdt.end_node(root)?;
+    fdt.finish()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_scoped_node_builder() -> Result<(), Error> {
+    let mut fdt = FdtWriter::new()?;
+
+    fdt.node("", |root| {
+        root.property_string("compatible", "linux,dummy-virt")?;
+        root.child("cpu@0", |cpu| {
+            cpu.property_string("device_type", "cpu")?;
+            cpu.property_phandle(1)
+        })?;
+        root.child("memory@80000000", |memory| {
+            memory.property_string("device_type", "memory")?;
+            memory.property_array_u64("reg", &[0x8000_0000, 0x4000_0000])
+        })
+    })?;
+
+    let blob = fdt.finish()?;
+    verify_header(&blob);
+
+    let reader = FdtReader::new(&blob)?;
+    assert_eq!(reader.get_property("/cpu@0", "device_type")?, b"cpu\0");
+    assert_eq!(reader.memory_ranges()?, vec![(0x8000_0000, 0x4000_0000)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_scoped_node_builder_propagates_error_and_still_closes() -> Result<(), Error> {
+    let mut fdt = FdtWriter::new()?;
+
+    let result = fdt.node("", |root| {
+        root.child("a", |child| child.property_phandle(1))?;
+        // Duplicate phandle: the closure fails, but the node it opened must
+        // still be closed so the tree stays well-formed.
+        root.child("b", |child| child.property_phandle(1))
+    });
+    assert_eq!(result, Err(Error::DuplicatePhandle));
+
+    // The root closed cleanly despite the inner error, so a sibling
+    // top-level node is free to open afterwards.
+    fdt.node("unrelated", |_| Ok(()))?;
+    let blob = fdt.finish()?;
+    verify_header(&blob);
+
+    Ok(())
+}
+
+#[test]
+fn test_label_ref_resolved_same_tree() -> Result<(), Error> {
+    let mut fdt = FdtWriter::new()?;
+    let root = fdt.begin_node("")?;
+
+    let intc = fdt.begin_node("interrupt-controller")?;
+    fdt.label_node(&intc, "intc")?;
+    fdt.property_phandle(5)?;
+    fdt.end_node(intc)?;
+
+    let dev = fdt.begin_node("uart@1000")?;
+    fdt.property_phandle_ref_label("interrupt-parent", "intc")?;
+    fdt.end_node(dev)?;
+
+    fdt.end_node(root)?;
+    let blob = fdt.finish()?;
+
+    let reader = FdtReader::new(&blob)?;
+    assert_eq!(
+        reader.get_property("/uart@1000", "interrupt-parent")?,
+        &5u32.to_be_bytes()
+    );
+    // The reference resolved immediately, so no __fixups__/__local_fixups__
+    // housekeeping node is needed.
+    assert_eq!(reader.properties_of("/__fixups__"), Err(Error::NotFound));
+
+    Ok(())
+}
+
+#[test]
+fn test_label_node_rejects_duplicate_label() -> Result<(), Error> {
+    let mut fdt = FdtWriter::new()?;
+    let root = fdt.begin_node("")?;
+
+    let a = fdt.begin_node("a")?;
+    fdt.label_node(&a, "dup")?;
+    fdt.end_node(a)?;
+
+    let b = fdt.begin_node("b")?;
+    assert_eq!(fdt.label_node(&b, "dup"), Err(Error::DuplicateLabel));
+    fdt.end_node(b)?;
+
+    fdt.end_node(root)?;
+    fdt.finish()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_apply_overlay_resolves_external_label() -> Result<(), Error> {
+    let mut base = FdtWriter::new()?;
+    let base_root = base.begin_node("")?;
+    let intc = base.begin_node("interrupt-controller")?;
+    base.label_node(&intc, "intc")?;
+    base.property_phandle(1)?;
+    base.end_node(intc)?;
+    base.end_node(base_root)?;
+    let base_blob = base.finish()?;
+
+    let mut overlay = FdtWriter::new()?;
+    let overlay_root = overlay.begin_node("")?;
+    let dev = overlay.begin_node("uart@2000")?;
+    overlay.property_string("compatible", "ns16550a")?;
+    overlay.property_phandle_ref_label("interrupt-parent", "intc")?;
+    overlay.end_node(dev)?;
+    overlay.end_node(overlay_root)?;
+    let overlay_blob = overlay.finish()?;
+
+    let merged = apply_overlay(&base_blob, &overlay_blob)?;
+    let reader = FdtReader::new(&merged)?;
+
+    assert_eq!(
+        reader.get_property("/interrupt-controller", "phandle")?,
+        &1u32.to_be_bytes()
+    );
+    assert_eq!(
+        reader.get_property("/uart@2000", "interrupt-parent")?,
+        &1u32.to_be_bytes()
+    );
+    assert_eq!(
+        reader.get_property("/uart@2000", "compatible")?,
+        b"ns16550a\0"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_apply_overlay_rejects_out_of_range_fixup_offset() -> Result<(), Error> {
+    let mut base = FdtWriter::new()?;
+    let base_root = base.begin_node("")?;
+    let intc = base.begin_node("interrupt-controller")?;
+    base.label_node(&intc, "intc")?;
+    base.property_phandle(1)?;
+    base.end_node(intc)?;
+    base.end_node(base_root)?;
+    let base_blob = base.finish()?;
+
+    let mut overlay = FdtWriter::new()?;
+    let overlay_root = overlay.begin_node("")?;
+    let dev = overlay.begin_node("uart@2000")?;
+    overlay.property_phandle_ref_label("interrupt-parent", "intc")?;
+    overlay.end_node(dev)?;
+    overlay.end_node(overlay_root)?;
+    let mut overlay_blob = overlay.finish()?;
+
+    // The generated `__fixups__` triplet ends in ":0" (the offset of the
+    // patched cell within the property). Corrupt it to an offset that
+    // can't possibly fit within the property's 4-byte value, simulating a
+    // hand-crafted malicious overlay, and confirm it's rejected with an
+    // error instead of panicking or silently writing out of bounds.
+    let needle = b":interrupt-parent:0";
+    let pos = overlay_blob
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .expect("generated overlay must contain the interrupt-parent fixup triplet");
+    overlay_blob[pos + needle.len() - 1] = b'9';
+
+    assert_eq!(
+        apply_overlay(&base_blob, &overlay_blob),
+        Err(Error::BadToken)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_apply_overlay_rejects_unresolved_local_fixup() -> Result<(), Error> {
+    let mut base = FdtWriter::new()?;
+    let base_root = base.begin_node("")?;
+    base.end_node(base_root)?;
+    let base_blob = base.finish()?;
+
+    let mut overlay = FdtWriter::new()?;
+    let overlay_root = overlay.begin_node("")?;
+    let a = overlay.begin_node("a")?;
+    overlay.property_phandle_ref_label("target", "tgt")?;
+    overlay.end_node(a)?;
+    let b = overlay.begin_node("b")?;
+    overlay.label_node(&b, "tgt")?;
+    overlay.end_node(b)?;
+    overlay.end_node(overlay_root)?;
+    let overlay_blob = overlay.finish()?;
+
+    assert_eq!(
+        apply_overlay(&base_blob, &overlay_blob),
+        Err(Error::UnresolvedFixup)
+    );
+
+    Ok(())
+}